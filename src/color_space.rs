@@ -0,0 +1,117 @@
+//! sRGB <-> CIELAB conversion helpers (D65 white point), used when clustering
+//! is done in a perceptually uniform color space instead of raw sRGB.
+
+const WHITE_X: f64 = 0.95047;
+const WHITE_Y: f64 = 1.0;
+const WHITE_Z: f64 = 1.08883;
+
+fn srgb_to_linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f64) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+fn rgb_to_xyz(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+    let r = srgb_to_linear(r);
+    let g = srgb_to_linear(g);
+    let b = srgb_to_linear(b);
+
+    let x = r * 0.4124564 + g * 0.3575761 + b * 0.1804375;
+    let y = r * 0.2126729 + g * 0.7151522 + b * 0.0721750;
+    let z = r * 0.0193339 + g * 0.1191920 + b * 0.9503041;
+    (x, y, z)
+}
+
+fn xyz_to_rgb(x: f64, y: f64, z: f64) -> (u8, u8, u8) {
+    let r = x * 3.2404542 + y * -1.5371385 + z * -0.4985314;
+    let g = x * -0.9692660 + y * 1.8760108 + z * 0.0415560;
+    let b = x * 0.0556434 + y * -0.2040259 + z * 1.0572252;
+    (linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+}
+
+fn lab_f(t: f64) -> f64 {
+    const DELTA: f64 = 6.0 / 29.0;
+
+    if t > DELTA.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f64) -> f64 {
+    const DELTA: f64 = 6.0 / 29.0;
+
+    if t > DELTA {
+        t.powi(3)
+    } else {
+        3.0 * DELTA * DELTA * (t - 4.0 / 29.0)
+    }
+}
+
+/// Converts an sRGB triple to CIE L*a*b* (D65 white point).
+pub fn rgb_to_lab(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+    let (x, y, z) = rgb_to_xyz(r, g, b);
+
+    let fx = lab_f(x / WHITE_X);
+    let fy = lab_f(y / WHITE_Y);
+    let fz = lab_f(z / WHITE_Z);
+
+    let l = 116.0 * fy - 16.0;
+    let a = 500.0 * (fx - fy);
+    let b = 200.0 * (fy - fz);
+    (l, a, b)
+}
+
+/// Converts a CIE L*a*b* triple (D65 white point) back to sRGB.
+pub fn lab_to_rgb(l: f64, a: f64, b: f64) -> (u8, u8, u8) {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+
+    let x = lab_f_inv(fx) * WHITE_X;
+    let y = lab_f_inv(fy) * WHITE_Y;
+    let z = lab_f_inv(fz) * WHITE_Z;
+
+    xyz_to_rgb(x, y, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_within_one_level_per_channel() {
+        for &(r, g, b) in &[
+            (0, 0, 0),
+            (255, 255, 255),
+            (255, 0, 0),
+            (0, 255, 0),
+            (0, 0, 255),
+            (128, 64, 200),
+            (17, 201, 99),
+        ] {
+            let (l, a, bb) = rgb_to_lab(r, g, b);
+            let (r2, g2, b2) = lab_to_rgb(l, a, bb);
+
+            assert!((r as i16 - r2 as i16).abs() <= 1, "r: {r} vs {r2}");
+            assert!((g as i16 - g2 as i16).abs() <= 1, "g: {g} vs {g2}");
+            assert!((b as i16 - b2 as i16).abs() <= 1, "b: {b} vs {b2}");
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Alternative output containers for the `--format` flag.
+
+use std::fs::File;
+use std::io::{self, Write};
+
+/// Writes a paletted container: a small fixed header, the RGB palette, and
+/// one index byte per pixel.
+pub fn write_indexed(
+    path: &str,
+    palette: &[[u8; 3]],
+    indices: &[u8],
+    width: u32,
+    height: u32,
+) -> io::Result<()> {
+    if palette.len() > 255 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "indexed output only supports up to 255 colors (palette count is a single byte)",
+        ));
+    }
+
+    let mut file = File::create(path)?;
+
+    file.write_all(b"IDXP")?;
+    file.write_all(&width.to_le_bytes())?;
+    file.write_all(&height.to_le_bytes())?;
+    file.write_all(&[palette.len() as u8])?;
+
+    for color in palette {
+        file.write_all(color)?;
+    }
+
+    file.write_all(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("image-compressor-test-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn rejects_a_palette_that_cannot_fit_in_the_count_byte() {
+        let palette = vec![[0u8, 0, 0]; 256];
+        let err = write_indexed(&temp_path("rejects"), &palette, &[], 1, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn header_palette_count_matches_the_written_palette() {
+        let path = temp_path("roundtrip");
+        let palette = vec![[1u8, 2, 3]; 255];
+        let indices = vec![0u8; 4];
+
+        write_indexed(&path, &palette, &indices, 2, 2).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[0..4], b"IDXP");
+        assert_eq!(bytes[12], 255);
+        assert_eq!(bytes.len(), 13 + palette.len() * 3 + indices.len());
+    }
+}
@@ -1,8 +1,13 @@
+mod color_space;
+mod format;
+mod median_cut;
+mod qoi;
+
 use image::{ImageBuffer, RgbImage};
 use rand::{distributions::Uniform, rngs::ThreadRng, Rng};
 use rayon::prelude::*;
 use std::sync::Mutex;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -15,13 +20,45 @@ struct Args{
 
     #[arg(short, long, default_value_t = 4)]
     k: usize,
+
+    /// Color space k-means clustering is performed in.
+    #[arg(long, value_enum, default_value_t = ColorSpace::Rgb)]
+    color_space: ColorSpace,
+
+    /// Output pixel format.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Rgb)]
+    format: OutputFormat,
+
+    /// Palette generation method. `median-cut` is a deterministic, single-pass
+    /// alternative to `kmeans` that is far faster on large images.
+    #[arg(long, value_enum, default_value_t = Method::Kmeans)]
+    method: Method,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorSpace {
+    Rgb,
+    Lab,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Rgb,
+    Indexed,
+    Qoi,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Method {
+    Kmeans,
+    MedianCut,
 }
 
 #[derive(Debug, Clone, Copy)]
 struct Pixel {
-    r: u8,
-    g: u8,
-    b: u8,
+    r: f64,
+    g: f64,
+    b: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -31,15 +68,38 @@ struct Centroid {
     b: f64,
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+struct Cluster {
+    sum_r: f64,
+    sum_g: f64,
+    sum_b: f64,
+    count: usize,
+    dist: f64,
+}
+
+impl Cluster {
+    fn centroid(&self) -> Centroid {
+        if self.count > 0 {
+            Centroid {
+                r: self.sum_r / self.count as f64,
+                g: self.sum_g / self.count as f64,
+                b: self.sum_b / self.count as f64,
+            }
+        } else {
+            Centroid { r: 0.0, g: 0.0, b: 0.0 }
+        }
+    }
+}
+
 struct KMeans {
     centroids: Vec<Centroid>,
 }
 
 impl Pixel {
     fn distance(&self, centroid: &Centroid) -> f64 {
-        ((self.r as f64 - centroid.r).powi(2)
-            + (self.g as f64 - centroid.g).powi(2)
-            + (self.b as f64 - centroid.b).powi(2))
+        ((self.r - centroid.r).powi(2)
+            + (self.g - centroid.g).powi(2)
+            + (self.b - centroid.b).powi(2))
             .sqrt()
     }
 }
@@ -61,9 +121,9 @@ impl KMeans {
             .map(|_| {
                 let pixel = &pixels[rng.sample(range)];
                 Centroid {
-                    r: pixel.r as f64,
-                    g: pixel.g as f64,
-                    b: pixel.b as f64,
+                    r: pixel.r,
+                    g: pixel.g,
+                    b: pixel.b,
                 }
             })
             .collect();
@@ -116,7 +176,7 @@ impl KMeans {
         let new_centroids: Vec<Centroid> = pixel_groups.iter().map(|group| {
             let group = group.lock().unwrap();
             let (sum_r, sum_g, sum_b, count) = group.iter().fold((0f64, 0f64, 0f64, 0), |(r, g, b, count), pixel| {
-                (r + pixel.r as f64, g + pixel.g as f64, b + pixel.b as f64, count + 1)
+                (r + pixel.r, g + pixel.g, b + pixel.b, count + 1)
             });
 
             if count > 0 {
@@ -132,6 +192,168 @@ impl KMeans {
 
         self.centroids = new_centroids;
     }
+
+    fn assign_pixels(&self, pixels: &Vec<Pixel>) -> Vec<usize> {
+        pixels
+            .par_iter()
+            .map(|pixel| closest_centroid_index(pixel, &self.centroids))
+            .collect()
+    }
+
+    fn build_clusters(&self, assignments: &Vec<usize>, pixels: &Vec<Pixel>) -> Vec<Cluster> {
+        let mut clusters = vec![Cluster::default(); self.centroids.len()];
+
+        for (i, &cluster_index) in assignments.iter().enumerate() {
+            let pixel = &pixels[i];
+            let cluster = &mut clusters[cluster_index];
+
+            cluster.sum_r += pixel.r;
+            cluster.sum_g += pixel.g;
+            cluster.sum_b += pixel.b;
+            cluster.count += 1;
+            cluster.dist += pixel.distance(&self.centroids[cluster_index]).powi(2);
+        }
+        clusters
+    }
+
+    /// Enhanced LBG (ELBG) refinement pass, run after Lloyd's algorithm converges.
+    ///
+    /// Repeatedly takes a low-distortion ("underused") cluster `l` and a
+    /// high-distortion cluster `h`, pools their pixels, and re-splits that pool
+    /// between two new centroids placed on either side of `h`'s centroid,
+    /// keeping the shift only when it strictly lowers the combined distortion
+    /// of the two clusters involved. Note this is a pooled-split variant, not
+    /// a full reassign-to-nearest-existing-centroid: `l`'s pixels are only ever
+    /// considered against the new `h`-derived split, not every other centroid.
+    /// This still helps escape the poor local minima plain k-means tends to
+    /// settle into, especially for larger `k`.
+    fn elbg_refine(&mut self, pixels: &Vec<Pixel>, max_rounds: usize) {
+        let k = self.centroids.len();
+        if k < 2 || pixels.is_empty() {
+            return;
+        }
+
+        let mut assignments = self.assign_pixels(pixels);
+
+        for _ in 0..max_rounds {
+            let clusters = self.build_clusters(&assignments, pixels);
+            let mean_dist = clusters.iter().map(|cluster| cluster.dist).sum::<f64>() / k as f64;
+
+            let mut members_by_cluster: Vec<Vec<usize>> = vec![Vec::new(); k];
+            for (i, &cluster_index) in assignments.iter().enumerate() {
+                members_by_cluster[cluster_index].push(i);
+            }
+
+            let mut low: Vec<usize> = (0..k).filter(|&i| clusters[i].dist < mean_dist).collect();
+            let mut high: Vec<usize> = (0..k)
+                .filter(|&i| clusters[i].dist >= mean_dist && clusters[i].count >= 2)
+                .collect();
+
+            low.sort_by(|&a, &b| clusters[a].dist.partial_cmp(&clusters[b].dist).unwrap());
+
+            let mut did_shift = false;
+
+            for l in low {
+                if high.is_empty() {
+                    break;
+                }
+
+                let mut best_shift: Option<(usize, Vec<(usize, usize)>, f64)> = None;
+
+                for &h in &high {
+                    if h == l {
+                        continue;
+                    }
+
+                    let h_cluster = clusters[h];
+                    let h_centroid = h_cluster.centroid();
+                    let offset = (h_cluster.dist / h_cluster.count as f64).sqrt().max(1e-6) * 0.5;
+
+                    let centroid_a = Centroid {
+                        r: h_centroid.r + offset,
+                        g: h_centroid.g + offset,
+                        b: h_centroid.b + offset,
+                    };
+                    let centroid_b = Centroid {
+                        r: h_centroid.r - offset,
+                        g: h_centroid.g - offset,
+                        b: h_centroid.b - offset,
+                    };
+
+                    let members = members_by_cluster[l].iter().chain(members_by_cluster[h].iter());
+
+                    let mut reassignments = Vec::with_capacity(clusters[l].count + clusters[h].count);
+                    let (mut dist_a, mut count_a) = (0f64, 0usize);
+                    let (mut dist_b, mut count_b) = (0f64, 0usize);
+
+                    for &i in members {
+                        let pixel = &pixels[i];
+                        let distance_a = pixel.distance(&centroid_a);
+                        let distance_b = pixel.distance(&centroid_b);
+
+                        if distance_a <= distance_b {
+                            reassignments.push((i, l));
+                            dist_a += distance_a.powi(2);
+                            count_a += 1;
+                        } else {
+                            reassignments.push((i, h));
+                            dist_b += distance_b.powi(2);
+                            count_b += 1;
+                        }
+                    }
+
+                    // A split that leaves one side empty isn't a real split.
+                    if count_a == 0 || count_b == 0 {
+                        continue;
+                    }
+
+                    let before = clusters[l].dist + clusters[h].dist;
+                    let after = dist_a + dist_b;
+
+                    if after < before
+                        && best_shift.as_ref().map_or(true, |(_, _, best_after)| after < *best_after)
+                    {
+                        best_shift = Some((h, reassignments, after));
+                    }
+                }
+
+                if let Some((h, reassignments, _)) = best_shift {
+                    let h_centroid = clusters[h].centroid();
+                    let offset = (clusters[h].dist / clusters[h].count as f64).sqrt().max(1e-6) * 0.5;
+
+                    self.centroids[l] = Centroid {
+                        r: h_centroid.r + offset,
+                        g: h_centroid.g + offset,
+                        b: h_centroid.b + offset,
+                    };
+                    self.centroids[h] = Centroid {
+                        r: h_centroid.r - offset,
+                        g: h_centroid.g - offset,
+                        b: h_centroid.b - offset,
+                    };
+
+                    for (i, cluster_index) in reassignments {
+                        assignments[i] = cluster_index;
+                    }
+
+                    // `h` just donated its pixels to this round's split; a
+                    // later `l` in this same round must not reuse the stale
+                    // pre-round snapshot of its members.
+                    high.retain(|&i| i != h);
+                    did_shift = true;
+                }
+            }
+
+            if !did_shift {
+                break;
+            }
+
+            // Let Lloyd's algorithm settle the centroids we just moved, then
+            // re-evaluate distortion for the next round.
+            self.update_centroids(&assignments, pixels);
+            assignments = self.assign_pixels(pixels);
+        }
+    }
 }
 
 fn main() {
@@ -139,37 +361,91 @@ fn main() {
     let input = args.input;
     let output = args.output;
     let k = args.k;
+    let color_space = args.color_space;
     let img = image::open(input).expect("Failed to open image").to_rgb8();
     let (width, height) = img.dimensions();
     let mut pixels: Vec<Pixel> = Vec::new();
 
     for (_, _, pixel) in img.enumerate_pixels() {
-        pixels.push(Pixel {
-            r: pixel[0],
-            g: pixel[1],
-            b: pixel[2],
-        });
+        let (r, g, b) = match color_space {
+            ColorSpace::Rgb => (pixel[0] as f64, pixel[1] as f64, pixel[2] as f64),
+            ColorSpace::Lab => color_space::rgb_to_lab(pixel[0], pixel[1], pixel[2]),
+        };
+        pixels.push(Pixel { r, g, b });
+    }
+    let centroids = match args.method {
+        Method::Kmeans => {
+            let mut rng = rand::thread_rng();
+            let mut kmeans = KMeans::new(k, &pixels, &mut rng);
+
+            kmeans.run(&pixels, 100);
+            kmeans.elbg_refine(&pixels, 20);
+            kmeans.centroids
+        }
+        Method::MedianCut => median_cut::build_palette(&pixels, k),
+    };
+
+    match args.format {
+        OutputFormat::Rgb => {
+            let output_img = reconstruct_image(&centroids, &pixels, width, height, color_space);
+            output_img.save(output).expect("Failed to save image");
+        }
+        OutputFormat::Indexed => {
+            let palette: Vec<[u8; 3]> = centroids
+                .iter()
+                .map(|centroid| centroid_to_rgb(centroid, color_space))
+                .map(|(r, g, b)| [r, g, b])
+                .collect();
+            let indices: Vec<u8> = pixels
+                .par_iter()
+                .map(|pixel| closest_centroid_index(pixel, &centroids) as u8)
+                .collect();
+
+            format::write_indexed(&output, &palette, &indices, width, height)
+                .expect("Failed to write indexed image");
+        }
+        OutputFormat::Qoi => {
+            let pixels = reconstruct_pixels(&centroids, &pixels, color_space);
+            let bytes = qoi::encode(&pixels, width, height);
+
+            std::fs::write(&output, bytes).expect("Failed to write QOI image");
+        }
     }
-    let mut rng = rand::thread_rng();
-    let mut kmeans = KMeans::new(k, &pixels, &mut rng);
+}
 
-    kmeans.run(&pixels, 100);
-    let output_img = reconstruct_image(&kmeans.centroids, &pixels, width, height);
+fn centroid_to_rgb(centroid: &Centroid, color_space: ColorSpace) -> (u8, u8, u8) {
+    match color_space {
+        ColorSpace::Rgb => (
+            centroid.r.round().clamp(0.0, 255.0) as u8,
+            centroid.g.round().clamp(0.0, 255.0) as u8,
+            centroid.b.round().clamp(0.0, 255.0) as u8,
+        ),
+        ColorSpace::Lab => color_space::lab_to_rgb(centroid.r, centroid.g, centroid.b),
+    }
+}
 
-    output_img.save(output).expect("Failed to save image");
+fn reconstruct_pixels(centroids: &Vec<Centroid>, pixels: &Vec<Pixel>, color_space: ColorSpace) -> Vec<[u8; 3]> {
+    pixels
+        .iter()
+        .map(|pixel| {
+            let centroid = &centroids[closest_centroid_index(pixel, centroids)];
+            let (r, g, b) = centroid_to_rgb(centroid, color_space);
+            [r, g, b]
+        })
+        .collect()
 }
 
-fn reconstruct_image(centroids: &Vec<Centroid>, pixels: &Vec<Pixel>, width: u32, height: u32) -> RgbImage {
+fn reconstruct_image(
+    centroids: &Vec<Centroid>,
+    pixels: &Vec<Pixel>,
+    width: u32,
+    height: u32,
+    color_space: ColorSpace,
+) -> RgbImage {
     let mut img: RgbImage = ImageBuffer::new(width, height);
 
-    for (i, pixel) in pixels.iter().enumerate() {
-        let centroid = &centroids[closest_centroid_index(pixel, centroids)];
-
-        img.put_pixel(
-            (i as u32) % width,
-            (i as u32) / width,
-            image::Rgb([centroid.r as u8, centroid.g as u8, centroid.b as u8]),
-        );
+    for (i, [r, g, b]) in reconstruct_pixels(centroids, pixels, color_space).into_iter().enumerate() {
+        img.put_pixel((i as u32) % width, (i as u32) / width, image::Rgb([r, g, b]));
     }
     img
 }
@@ -187,3 +463,63 @@ fn closest_centroid_index(pixel: &Pixel, centroids: &Vec<Centroid>) -> usize {
         .map(|(index, _)| index)
         .unwrap_or(0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elbg_refine_does_not_clobber_a_shift_with_another_in_the_same_round() {
+        let pixels: Vec<Pixel> = [
+            (0.0, 0.0, 0.0),
+            (5.0, 5.0, 5.0),
+            (100.0, 100.0, 100.0),
+            (100.0, 100.0, 100.0),
+            (100.0, 100.0, 100.0),
+            (100.0, 100.0, 100.0),
+            (100.0, 100.0, 100.0),
+            (100.0, 100.0, 100.0),
+            (100.0, 100.0, 100.0),
+            (100.0, 100.0, 100.0),
+            (200.0, 200.0, 200.0),
+            (200.0, 200.0, 200.0),
+            (200.0, 200.0, 200.0),
+            (200.0, 200.0, 200.0),
+            (200.0, 200.0, 200.0),
+            (200.0, 200.0, 200.0),
+            (200.0, 200.0, 200.0),
+            (200.0, 200.0, 200.0),
+        ]
+        .iter()
+        .map(|&(r, g, b)| Pixel { r, g, b })
+        .collect();
+
+        let mut kmeans = KMeans {
+            centroids: vec![
+                Centroid { r: 0.0, g: 0.0, b: 0.0 },
+                Centroid { r: 5.0, g: 5.0, b: 5.0 },
+                Centroid { r: 150.0, g: 150.0, b: 150.0 },
+            ],
+        };
+
+        // Two underused low-distortion clusters (near the origin and near
+        // (5, 5, 5)) compete in the same round for the same high-distortion
+        // bimodal cluster. Either trial alone would be accepted against it,
+        // but only the first may be applied, so nothing should end up empty:
+        // each of the two real blobs must land with exactly one centroid, and
+        // the other low cluster keeps its own pixel.
+        kmeans.elbg_refine(&pixels, 1);
+
+        let assignments: Vec<usize> = pixels
+            .iter()
+            .map(|pixel| closest_centroid_index(pixel, &kmeans.centroids))
+            .collect();
+        let counts: Vec<usize> = (0..3)
+            .map(|cluster| assignments.iter().filter(|&&a| a == cluster).count())
+            .collect();
+
+        assert!(counts.iter().all(|&count| count > 0), "no cluster should end up empty: {counts:?}");
+        assert_eq!(counts.iter().sum::<usize>(), pixels.len());
+        assert_eq!(assignments[1], 1, "the second low cluster should keep its own pixel");
+    }
+}
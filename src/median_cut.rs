@@ -0,0 +1,123 @@
+//! Median-cut palette generation: a deterministic, single-pass alternative
+//! to iterative k-means clustering, and a good seed generator for it too.
+
+use crate::{Centroid, Pixel};
+
+struct ColorBox {
+    pixels: Vec<Pixel>,
+}
+
+impl ColorBox {
+    /// Returns the index (0 = r, 1 = g, 2 = b) and size of this box's widest
+    /// channel extent.
+    fn widest_channel(&self) -> (usize, f64) {
+        let mut min = [f64::MAX; 3];
+        let mut max = [f64::MIN; 3];
+
+        for pixel in &self.pixels {
+            let channels = [pixel.r, pixel.g, pixel.b];
+
+            for i in 0..3 {
+                min[i] = min[i].min(channels[i]);
+                max[i] = max[i].max(channels[i]);
+            }
+        }
+
+        (0..3)
+            .map(|i| (i, max[i] - min[i]))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap()
+    }
+
+    fn centroid(&self) -> Centroid {
+        let (sum_r, sum_g, sum_b, count) = self
+            .pixels
+            .iter()
+            .fold((0f64, 0f64, 0f64, 0usize), |(r, g, b, count), pixel| {
+                (r + pixel.r, g + pixel.g, b + pixel.b, count + 1)
+            });
+
+        if count > 0 {
+            Centroid {
+                r: sum_r / count as f64,
+                g: sum_g / count as f64,
+                b: sum_b / count as f64,
+            }
+        } else {
+            Centroid { r: 0.0, g: 0.0, b: 0.0 }
+        }
+    }
+
+    /// Sorts this box's pixels along `channel` and splits it into two boxes
+    /// at the median. `channel` is expected to be this box's widest, as
+    /// returned by a prior call to `widest_channel`.
+    fn split(mut self, channel: usize) -> (ColorBox, ColorBox) {
+        self.pixels.sort_by(|a, b| {
+            let ca = [a.r, a.g, a.b][channel];
+            let cb = [b.r, b.g, b.b][channel];
+            ca.partial_cmp(&cb).unwrap()
+        });
+
+        let upper_half = self.pixels.split_off(self.pixels.len() / 2);
+        (ColorBox { pixels: self.pixels }, ColorBox { pixels: upper_half })
+    }
+}
+
+/// Builds a palette of at most `k` colors via median-cut: starting from one
+/// box spanning all pixels, repeatedly split the box with the largest channel
+/// extent at its median until `k` boxes exist, then take each box's mean pixel
+/// as its palette color. Splitting stops early, returning fewer than `k`
+/// colors, once no box holds more than one distinct color.
+pub fn build_palette(pixels: &Vec<Pixel>, k: usize) -> Vec<Centroid> {
+    if pixels.is_empty() || k == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![ColorBox { pixels: pixels.clone() }];
+
+    while boxes.len() < k {
+        let splittable = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.pixels.len() >= 2)
+            .map(|(i, color_box)| (i, color_box.widest_channel()))
+            .filter(|(_, (_, extent))| *extent > 0.0)
+            .max_by(|(_, (_, a)), (_, (_, b))| a.partial_cmp(b).unwrap())
+            .map(|(i, (channel, _))| (i, channel));
+
+        let Some((index, channel)) = splittable else {
+            break;
+        };
+
+        let (a, b) = boxes.remove(index).split(channel);
+        boxes.push(a);
+        boxes.push(b);
+    }
+
+    boxes.iter().map(ColorBox::centroid).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(r: f64, g: f64, b: f64) -> Pixel {
+        Pixel { r, g, b }
+    }
+
+    #[test]
+    fn returns_k_colors_when_enough_unique_pixels_exist() {
+        let pixels: Vec<Pixel> = (0..64)
+            .map(|i| pixel(i as f64 * 4.0, (i * 7) as f64 % 255.0, (i * 13) as f64 % 255.0))
+            .collect();
+
+        assert_eq!(build_palette(&pixels, 8).len(), 8);
+    }
+
+    #[test]
+    fn degrades_gracefully_when_there_are_fewer_unique_colors_than_k() {
+        let pixels = vec![pixel(10.0, 10.0, 10.0); 5];
+
+        assert_eq!(build_palette(&pixels, 8).len(), 1);
+    }
+}
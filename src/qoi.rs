@@ -0,0 +1,112 @@
+//! Minimal QOI (Quite OK Image, https://qoiformat.org) encoder for the
+//! reconstructed RGB pixel stream.
+
+const QOI_OP_INDEX: u8 = 0x00; // 00xxxxxx
+const QOI_OP_DIFF: u8 = 0x40; // 01xxxxxx
+const QOI_OP_LUMA: u8 = 0x80; // 10xxxxxx
+const QOI_OP_RUN: u8 = 0xc0; // 11xxxxxx
+const QOI_OP_RGB: u8 = 0xfe;
+
+const HEADER_SIZE: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+fn hash_index(pixel: [u8; 4]) -> usize {
+    let [r, g, b, a] = pixel;
+    (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64
+}
+
+/// Encodes an opaque RGB pixel buffer (row-major, `width * height` entries)
+/// into a QOI byte stream.
+pub fn encode(pixels: &[[u8; 3]], width: u32, height: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + pixels.len() * 2 + END_MARKER.len());
+
+    out.extend_from_slice(b"qoif");
+    out.extend_from_slice(&width.to_be_bytes());
+    out.extend_from_slice(&height.to_be_bytes());
+    out.push(3); // channels: RGB, no alpha
+    out.push(0); // colorspace: sRGB with linear alpha
+
+    let mut seen = [[0u8; 4]; 64];
+    let mut prev = [0u8, 0u8, 0u8, 255u8];
+    let mut run = 0u8;
+
+    for (i, &[r, g, b]) in pixels.iter().enumerate() {
+        let pixel = [r, g, b, 255];
+
+        if pixel == prev {
+            run += 1;
+            if run == 62 || i == pixels.len() - 1 {
+                out.push(QOI_OP_RUN | (run - 1));
+                run = 0;
+            }
+            prev = pixel;
+            continue;
+        }
+
+        if run > 0 {
+            out.push(QOI_OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let index = hash_index(pixel);
+
+        if seen[index] == pixel {
+            out.push(QOI_OP_INDEX | index as u8);
+        } else {
+            seen[index] = pixel;
+
+            let dr = r.wrapping_sub(prev[0]) as i8;
+            let dg = g.wrapping_sub(prev[1]) as i8;
+            let db = b.wrapping_sub(prev[2]) as i8;
+            let dr_dg = dr.wrapping_sub(dg);
+            let db_dg = db.wrapping_sub(dg);
+
+            if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                out.push(QOI_OP_DIFF | (((dr + 2) as u8) << 4) | (((dg + 2) as u8) << 2) | (db + 2) as u8);
+            } else if (-32..=31).contains(&dg) && (-8..=7).contains(&dr_dg) && (-8..=7).contains(&db_dg) {
+                out.push(QOI_OP_LUMA | (dg + 32) as u8);
+                out.push((((dr_dg + 8) as u8) << 4) | (db_dg + 8) as u8);
+            } else {
+                out.push(QOI_OP_RGB);
+                out.push(r);
+                out.push(g);
+                out.push(b);
+            }
+        }
+
+        prev = pixel;
+    }
+
+    out.extend_from_slice(&END_MARKER);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_a_run_and_a_literal_pixel() {
+        let pixels = [[10, 20, 30], [10, 20, 30], [10, 20, 30], [200, 90, 5]];
+        let out = encode(&pixels, 4, 1);
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"qoif");
+        expected.extend_from_slice(&4u32.to_be_bytes());
+        expected.extend_from_slice(&1u32.to_be_bytes());
+        expected.push(3);
+        expected.push(0);
+        expected.push(QOI_OP_RGB);
+        expected.push(10);
+        expected.push(20);
+        expected.push(30);
+        expected.push(QOI_OP_RUN | 1); // 2 repeats of the same pixel after the first
+        expected.push(QOI_OP_RGB);
+        expected.push(200);
+        expected.push(90);
+        expected.push(5);
+        expected.extend_from_slice(&END_MARKER);
+
+        assert_eq!(out, expected);
+    }
+}